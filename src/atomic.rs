@@ -0,0 +1,48 @@
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use serde::Serialize;
+
+/// Writes `value` as pretty JSON to `path` without ever leaving a partially
+/// written file behind. The new content is staged in a sibling `.tmp` file,
+/// flushed to disk, and only then moved into place with a rename, which is
+/// atomic on the same filesystem. The previous contents of `path` (if any)
+/// are preserved as a `.bak` file beforehand so a known-good copy always
+/// survives a bad write.
+pub fn write_json_atomic<T: Serialize>(path: &str, value: &T) -> io::Result<()> {
+    let tmp_path = format!("{path}.tmp");
+    let bak_path = format!("{path}.bak");
+
+    if Path::new(path).exists() {
+        fs::copy(path, &bak_path)?;
+    }
+    // A stale temp file left over from a previous crash would otherwise make
+    // `create_new` fail before we even get a chance to write.
+    let _ = fs::remove_file(&tmp_path);
+
+    let result = (|| {
+        let mut tmp_options = OpenOptions::new();
+        tmp_options.write(true).create_new(true);
+        #[cfg(unix)]
+        tmp_options.mode(0o600);
+        let mut file = tmp_options.open(&tmp_path)?;
+        serde_json::to_writer_pretty(&mut file, value)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        file.sync_data()
+    })();
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use std::io::Write;
+use std::sync::Mutex;
+
+use chrono::Local;
+use serde::Serialize;
+use tracing_appender::rolling::{self, RollingFileAppender};
+
+use crate::DATE_FORMAT_STR;
+
+#[derive(Serialize)]
+struct AuditEntry<'a> {
+    timestamp: String,
+    target: &'a str,
+    gear_changes: &'a [String],
+    setting_changes: &'a [String],
+}
+
+/// Appends one JSON line per actual file mutation to a daily-rotating log,
+/// so a user can reconstruct when and why a profile/settings file was
+/// rewritten.
+pub struct AuditLog {
+    writer: Mutex<RollingFileAppender>,
+}
+
+impl AuditLog {
+    pub fn new(directory: &str) -> Self {
+        AuditLog {
+            writer: Mutex::new(rolling::daily(directory, "audit.log")),
+        }
+    }
+
+    pub fn record(&self, target: &str, gear_changes: &[String], setting_changes: &[String]) {
+        if gear_changes.is_empty() && setting_changes.is_empty() {
+            return;
+        }
+        let entry = AuditEntry {
+            timestamp: Local::now().format(DATE_FORMAT_STR).to_string(),
+            target,
+            gear_changes,
+            setting_changes,
+        };
+        let line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::error!("failed to serialize audit log entry: {e}");
+                return;
+            }
+        };
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(e) = writeln!(writer, "{line}") {
+            tracing::error!("failed to write audit log entry: {e}");
+        }
+    }
+}
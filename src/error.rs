@@ -0,0 +1,72 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
+
+/// Errors surfaced by the HTTP handlers. Each variant maps to a specific
+/// status code instead of panicking the request thread (or, for the file
+/// watcher, the whole process).
+#[derive(Debug)]
+pub enum AppError {
+    ProfileRead(std::io::Error),
+    ProfileParse(serde_json::Error),
+    SettingsRead(std::io::Error),
+    SettingsParse(serde_json::Error),
+    WriteFailed(std::io::Error),
+    UnknownTarget(String),
+    AmbiguousTarget,
+}
+
+impl AppError {
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::ProfileRead(_) | AppError::SettingsRead(_) => StatusCode::NOT_FOUND,
+            AppError::ProfileParse(_) | AppError::SettingsParse(_) => StatusCode::BAD_REQUEST,
+            AppError::WriteFailed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::UnknownTarget(_) => StatusCode::NOT_FOUND,
+            AppError::AmbiguousTarget => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            AppError::ProfileRead(_) => "profile_read",
+            AppError::ProfileParse(_) => "profile_parse",
+            AppError::SettingsRead(_) => "settings_read",
+            AppError::SettingsParse(_) => "settings_parse",
+            AppError::WriteFailed(_) => "write_failed",
+            AppError::UnknownTarget(_) => "unknown_target",
+            AppError::AmbiguousTarget => "ambiguous_target",
+        }
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::ProfileRead(e) => write!(f, "failed to read profile file: {e}"),
+            AppError::ProfileParse(e) => write!(f, "profile file is not valid json: {e}"),
+            AppError::SettingsRead(e) => write!(f, "failed to read settings file: {e}"),
+            AppError::SettingsParse(e) => write!(f, "settings file is not valid json: {e}"),
+            AppError::WriteFailed(e) => write!(f, "failed to write file: {e}"),
+            AppError::UnknownTarget(name) => write!(f, "no target named '{name}'"),
+            AppError::AmbiguousTarget => write!(
+                f,
+                "no target specified and more than one target is configured"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let body = Json(json!({
+            "error": self.to_string(),
+            "kind": self.kind(),
+        }));
+        (status, body).into_response()
+    }
+}
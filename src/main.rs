@@ -1,73 +1,123 @@
+mod atomic;
+mod audit;
+mod error;
+mod store;
+
 use std::collections::HashMap;
 use std::env::current_dir;
 use std::fmt::{Debug, Formatter};
 use std::fs;
-use std::fs::OpenOptions;
-use std::panic::panic_any;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use axum::{Json, Router};
-use axum::extract::State;
-use axum::http::StatusCode;
+use axum::extract::{Query, State};
+use axum::extract::Path as AxumPath;
 use axum::routing::post;
-use chrono::Local;
 use notify_debouncer_mini::{DebounceEventHandler, DebounceEventResult, new_debouncer};
 use notify_debouncer_mini::notify::RecursiveMode;
 use serde::{de, Deserialize, Deserializer, Serialize};
 use serde::de::{MapAccess, Visitor};
 use serde_json::{json, Value};
 use tower_http::cors::CorsLayer;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::EnvFilter;
+
+use audit::AuditLog;
+use error::AppError;
 
 const DATE_FORMAT_STR: &str = "%Y-%m-%d %H:%M:%S%.3f";
 #[derive(Clone)]
 struct AppState {
-    settings: Arc<RwLock<Settings>>,
-    optimizer: Arc<RwLock<Option<Vec<Optimizer>>>>,
+    targets: Arc<RwLock<Vec<Target>>>,
+    optimizer: Arc<RwLock<HashMap<String, Vec<Optimizer>>>>,
+    store: sled::Db,
+    audit: Arc<AuditLog>,
+    // Tracks the mtime left by the server's own writes to a target's
+    // settings file, so the watcher can tell its own rewrite apart from a
+    // genuine external edit and avoid refreshing in a loop.
+    recent_writes: Arc<RwLock<HashMap<String, SystemTime>>>,
+}
+
+fn mark_self_write(state: &AppState, path: &str) {
+    if let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) {
+        state
+            .recent_writes
+            .write()
+            .unwrap()
+            .insert(path.to_string(), modified);
+    }
+}
+
+fn is_own_write(state: &AppState, path: &str) -> bool {
+    let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) else {
+        return false;
+    };
+    state.recent_writes.read().unwrap().get(path) == Some(&modified)
 }
 
 impl DebounceEventHandler for AppState {
     fn handle_event(&mut self, res: DebounceEventResult) {
         match res {
-            Ok(event) => {
-                println!(
-                    "{} Settings file refreshed",
-                    Local::now().format(DATE_FORMAT_STR)
-                );
-                if let Some(s) = event.first().map(|e| load_settings(&e.path)) {
-                    let mut guard = self.settings.write().unwrap();
+            Ok(events) => {
+                let targets = self.targets.read().unwrap();
+                for event in &events {
+                    let Some(target) = targets
+                        .iter()
+                        .find(|t| Path::new(t.settings_path()) == event.path)
+                    else {
+                        continue;
+                    };
+                    if is_own_write(self, target.settings_path()) {
+                        debug!(target_name = %target.name, "ignoring settings change caused by our own write");
+                        continue;
+                    }
+                    info!(target_name = %target.name, "settings file refreshed");
                     let optimizer_guard = self.optimizer.read().unwrap();
-                    if let Some(o) = optimizer_guard.as_ref() {
-                        println!(
-                            "{} Refreshing builds with last optimizer data.",
-                            Local::now().format(DATE_FORMAT_STR)
-                        );
-                        update_game_files(o, s.file_path(), s.settings_path(), s.settings_mapper());
-                        println!("{} Files Updated", Local::now().format(DATE_FORMAT_STR));
+                    if let Some(o) = optimizer_guard.get(&target.name) {
+                        info!(target_name = %target.name, "refreshing build with last optimizer data");
+                        match update_game_files(
+                            o,
+                            target.file_path(),
+                            target.settings_path(),
+                            target.settings_mapper(),
+                            false,
+                        ) {
+                            Ok(report) => {
+                                info!(target_name = %target.name, "files updated");
+                                if !report.setting_changes.is_empty() {
+                                    mark_self_write(self, target.settings_path());
+                                }
+                                record_audit(&self.audit, &target.name, &report);
+                            }
+                            Err(e) => {
+                                error!(target_name = %target.name, "failed to refresh target after settings change: {e}")
+                            }
+                        }
                     } else {
-                        println!(
-                            "{} No previous optimizer result found, skipping refresh.",
-                            Local::now().format(DATE_FORMAT_STR)
-                        )
+                        warn!(
+                            target_name = %target.name,
+                            "no previous optimizer result found, skipping refresh"
+                        );
                     }
-                    *guard = s;
                 }
             }
-            Err(e) => panic_any(e),
+            Err(e) => error!("settings watcher error, skipping refresh: {e}"),
         }
     }
 }
 
 #[derive(Clone, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct Settings {
+struct Target {
+    name: String,
     file_path: String,
     settings_path: String,
     settings_mapper: HashMap<String, String>,
 }
 
-impl Settings {
+impl Target {
     pub fn file_path(&self) -> &str {
         &self.file_path
     }
@@ -79,20 +129,42 @@ impl Settings {
     }
 }
 
+fn resolve_target<'a>(targets: &'a [Target], name: Option<&str>) -> Result<&'a Target, AppError> {
+    match name {
+        Some(name) => targets
+            .iter()
+            .find(|t| t.name == name)
+            .ok_or_else(|| AppError::UnknownTarget(name.to_string())),
+        None => match targets {
+            [single] => Ok(single),
+            _ => Err(AppError::AmbiguousTarget),
+        },
+    }
+}
+
 #[tokio::main]
 async fn main() {
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
     let state_path = current_dir()
         .expect("Current dir not found")
         .join("settings.json");
     let state = load_state(&state_path);
     let mut debouncer =
         new_debouncer(Duration::from_secs(1), state.clone()).expect("Failed to create debouncer");
-    debouncer
-        .watcher()
-        .watch(state_path.as_path(), RecursiveMode::NonRecursive)
-        .expect("Failed to watch settings file");
+    {
+        let targets = state.targets.read().unwrap();
+        for target in targets.iter() {
+            debouncer
+                .watcher()
+                .watch(Path::new(target.settings_path()), RecursiveMode::NonRecursive)
+                .expect("Failed to watch target settings file");
+        }
+    }
     let app = Router::new()
-        .route("/", post(update_files))
+        .route("/", post(update_files_default))
+        .route("/targets/:name", post(update_files_named))
         .layer(CorsLayer::permissive())
         .with_state(state);
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
@@ -100,43 +172,132 @@ async fn main() {
 }
 
 fn load_state(path_buf: &PathBuf) -> AppState {
+    let targets = load_settings(path_buf).expect("Error loading settings.json");
+    let db = store::open_db();
+    let mut optimizer = HashMap::new();
+    for target in &targets {
+        let key = store::target_key(target.file_path(), target.settings_path());
+        if let Some(o) = store::load_build(&db, &key) {
+            optimizer.insert(target.name.clone(), o);
+        }
+    }
     AppState {
-        settings: Arc::new(RwLock::new(load_settings(path_buf))),
-        optimizer: Arc::new(RwLock::new(None)),
+        targets: Arc::new(RwLock::new(targets)),
+        optimizer: Arc::new(RwLock::new(optimizer)),
+        store: db,
+        audit: Arc::new(AuditLog::new("logs")),
+        recent_writes: Arc::new(RwLock::new(HashMap::new())),
     }
 }
 
-fn load_settings(path_buf: &PathBuf) -> Settings {
-    let settings_str = fs::read_to_string(path_buf).expect("Error during reading settings.json");
-    serde_json::from_str(&settings_str).expect("Loading state failed")
+fn record_audit(audit: &AuditLog, target: &str, report: &ChangeReport) {
+    let gear_changes: Vec<String> = report
+        .gear_changes
+        .iter()
+        .map(|g| g.comment.clone())
+        .collect();
+    let setting_changes: Vec<String> = report
+        .setting_changes
+        .iter()
+        .map(|s| s.key.clone())
+        .collect();
+    audit.record(target, &gear_changes, &setting_changes);
+}
+
+fn load_settings(path_buf: &PathBuf) -> Result<Vec<Target>, AppError> {
+    let settings_str = fs::read_to_string(path_buf).map_err(AppError::SettingsRead)?;
+    serde_json::from_str(&settings_str).map_err(AppError::SettingsParse)
+}
+
+#[derive(Deserialize)]
+struct UpdateFilesParams {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct ChangeReport {
+    target: String,
+    gear_changes: Vec<GearChange>,
+    setting_changes: Vec<SettingChange>,
+    dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct GearChange {
+    comment: String,
+    old_ids: Vec<u32>,
+    new_ids: Vec<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct SettingChange {
+    key: String,
+    old_value: Value,
+    new_value: Value,
+}
+
+async fn update_files_default(
+    state: State<AppState>,
+    query: Query<UpdateFilesParams>,
+    json: Json<Vec<Optimizer>>,
+) -> Result<Json<ChangeReport>, AppError> {
+    update_files(state, None, query, json).await
+}
+
+async fn update_files_named(
+    state: State<AppState>,
+    AxumPath(name): AxumPath<String>,
+    query: Query<UpdateFilesParams>,
+    json: Json<Vec<Optimizer>>,
+) -> Result<Json<ChangeReport>, AppError> {
+    update_files(state, Some(name), query, json).await
 }
 
 async fn update_files(
     State(state): State<AppState>,
+    target_name: Option<String>,
+    Query(params): Query<UpdateFilesParams>,
     Json(optimizer): Json<Vec<Optimizer>>,
-) -> StatusCode {
-    println!(
-        "{} Optimizer request received",
-        Local::now().format(DATE_FORMAT_STR)
-    );
-    let settings = state.settings.read().unwrap();
+) -> Result<Json<ChangeReport>, AppError> {
+    info!(target_name = %target_name.as_deref().unwrap_or("<default>"), "optimizer request received");
+    let targets = state.targets.read().unwrap();
+    let target = resolve_target(&targets, target_name.as_deref())?;
     let mut optimizer_guard = state.optimizer.write().unwrap();
-    if optimizer_guard.as_ref().map_or(true, |v| v != &optimizer) {
-        *optimizer_guard = Some(optimizer);
-        update_game_files(
-            optimizer_guard.as_ref().unwrap(),
-            settings.file_path(),
-            settings.settings_path(),
-            settings.settings_mapper(),
-        );
-        println!("{} Files Updated", Local::now().format(DATE_FORMAT_STR));
+    let unchanged = optimizer_guard
+        .get(&target.name)
+        .is_some_and(|v| v == &optimizer);
+    if params.dry_run || !unchanged {
+        let mut report = update_game_files(
+            &optimizer,
+            target.file_path(),
+            target.settings_path(),
+            target.settings_mapper(),
+            params.dry_run,
+        )?;
+        report.target.clone_from(&target.name);
+        report.dry_run = params.dry_run;
+        if !params.dry_run {
+            let key = store::target_key(target.file_path(), target.settings_path());
+            store::save_build(&state.store, &key, &optimizer);
+            if !report.setting_changes.is_empty() {
+                mark_self_write(&state, target.settings_path());
+            }
+            optimizer_guard.insert(target.name.clone(), optimizer);
+            info!(target_name = %target.name, "files updated");
+            record_audit(&state.audit, &target.name, &report);
+        }
+        Ok(Json(report))
     } else {
-        println!(
-            "{} Optimizer result is same. Files won't be updated.",
-            Local::now().format(DATE_FORMAT_STR)
-        )
+        warn!(
+            target_name = %target.name,
+            "optimizer result is same, files won't be updated"
+        );
+        Ok(Json(ChangeReport {
+            target: target.name.clone(),
+            ..ChangeReport::default()
+        }))
     }
-    StatusCode::OK
 }
 
 fn update_game_files(
@@ -144,71 +305,90 @@ fn update_game_files(
     file_path: &str,
     settings_path: &str,
     settings_mapper: &HashMap<String, String>,
-) {
+    dry_run: bool,
+) -> Result<ChangeReport, AppError> {
     let optimizer_map: HashMap<_, _> = optimizer.iter().map(|o| (&o.label, &o.ids)).collect();
-    update_profile(file_path, &optimizer_map);
-    update_settings(settings_path, settings_mapper, &optimizer_map);
+    let gear_changes = update_profile(file_path, &optimizer_map, dry_run)?;
+    let setting_changes = update_settings(settings_path, settings_mapper, &optimizer_map, dry_run)?;
+    Ok(ChangeReport {
+        target: String::new(),
+        gear_changes,
+        setting_changes,
+        dry_run,
+    })
 }
 
 fn update_settings(
     settings_path: &str,
     settings_mapper: &HashMap<String, String>,
     optimizer_map: &HashMap<&String, &Vec<u32>>,
-) {
-    let file = fs::File::open(settings_path).expect("Profile read failed");
-    let mut settings: Value = serde_json::from_reader(file).expect("Settings is not valid json");
+    dry_run: bool,
+) -> Result<Vec<SettingChange>, AppError> {
+    let file = fs::File::open(settings_path).map_err(AppError::SettingsRead)?;
+    let mut settings: Value =
+        serde_json::from_reader(file).map_err(AppError::SettingsParse)?;
+    let mut changes = Vec::new();
     for (optimizer_label, setting_label) in settings_mapper.iter() {
         if let Some(value) = settings.get_mut(setting_label) {
             if let Some(ids) = optimizer_map.get(&optimizer_label) {
-                if let Some(value_ids) = value.as_array() {
-                    let gear_ids: Vec<u32> = value_ids
-                        .iter()
-                        .flat_map(|v| v.as_u64().map(|x| x as u32))
-                        .collect();
-                    if !vectors_equal(&gear_ids, ids) {
-                        println!("Setting {setting_label} updated with {optimizer_label}");
-                        *value = json!(ids);
+                let new_value = json!(ids);
+                let is_changed = match value.as_array() {
+                    Some(value_ids) => {
+                        let gear_ids: Vec<u32> = value_ids
+                            .iter()
+                            .flat_map(|v| v.as_u64().map(|x| x as u32))
+                            .collect();
+                        !vectors_equal(&gear_ids, ids)
                     }
-                } else {
-                    println!("Setting {setting_label} updated with {optimizer_label}");
-                    *value = json!(ids);
+                    None => true,
+                };
+                if is_changed {
+                    debug!("setting {setting_label} updated with {optimizer_label}");
+                    changes.push(SettingChange {
+                        key: setting_label.clone(),
+                        old_value: value.clone(),
+                        new_value: new_value.clone(),
+                    });
+                    *value = new_value;
                 }
             }
         }
     }
-    let mut file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(settings_path)
-        .expect("Failed to open file for writing");
-    serde_json::to_writer_pretty(&mut file, &settings).expect("Failed to serialize json");
+    if !dry_run && !changes.is_empty() {
+        atomic::write_json_atomic(settings_path, &settings).map_err(AppError::WriteFailed)?;
+    }
+    Ok(changes)
 }
 
-fn update_profile(profile_path: &str, optimizer_map: &HashMap<&String, &Vec<u32>>) {
-    let file = fs::File::open(profile_path).expect("Profile read failed");
-    let mut profile: Profile = serde_json::from_reader(file).expect("Profile is not valid json");
+fn update_profile(
+    profile_path: &str,
+    optimizer_map: &HashMap<&String, &Vec<u32>>,
+    dry_run: bool,
+) -> Result<Vec<GearChange>, AppError> {
+    let file = fs::File::open(profile_path).map_err(AppError::ProfileRead)?;
+    let mut profile: Profile =
+        serde_json::from_reader(file).map_err(AppError::ProfileParse)?;
 
+    let mut changes = Vec::new();
     for gear in &mut profile.breakpoints.gear {
         if let Some(comment) = &gear.comment {
             if let Some(ids) = optimizer_map.get(comment) {
                 if !vectors_equal(&gear.id, ids) {
+                    changes.push(GearChange {
+                        comment: comment.clone(),
+                        old_ids: gear.id.clone(),
+                        new_ids: (*ids).clone(),
+                    });
                     gear.id.clone_from(ids);
-                    println!(
-                        "{} Gear object with {comment} is updated",
-                        Local::now().format(DATE_FORMAT_STR)
-                    )
+                    debug!("gear object with {comment} is updated");
                 }
             }
         }
     }
-    let mut file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(profile_path)
-        .expect("Failed to open file for writing");
-    serde_json::to_writer_pretty(&mut file, &profile).expect("Failed to serialize json");
+    if !dry_run && !changes.is_empty() {
+        atomic::write_json_atomic(profile_path, &profile).map_err(AppError::WriteFailed)?;
+    }
+    Ok(changes)
 }
 
 #[derive(Debug)]
@@ -230,6 +410,18 @@ impl PartialEq for Optimizer {
     }
 }
 
+impl Serialize for Optimizer {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(&self.label, &self.ids)?;
+        map.end()
+    }
+}
+
 impl<'de> Deserialize<'de> for Optimizer {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -0,0 +1,71 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::Optimizer;
+
+const TREE_NAME: &str = "optimizer_builds";
+
+/// Opens the embedded key-value store used to persist the last accepted
+/// optimizer build across restarts. The database lives under the user's
+/// data directory so it survives the server being started from different
+/// working directories.
+pub fn open_db() -> sled::Db {
+    let db_path = dirs::data_dir()
+        .unwrap_or_else(|| std::env::current_dir().expect("Current dir not found"))
+        .join("nguserver")
+        .join("optimizer_store");
+    sled::open(db_path).expect("Failed to open optimizer store")
+}
+
+/// Derives a stable key for a watched target from the paths it writes to,
+/// so multiple watched targets sharing one store don't collide.
+pub fn target_key(file_path: &str, settings_path: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    file_path.hash(&mut hasher);
+    settings_path.hash(&mut hasher);
+    format!("{:x}", hasher.finish())
+}
+
+pub fn save_build(db: &sled::Db, key: &str, optimizer: &[Optimizer]) {
+    let tree = db.open_tree(TREE_NAME).expect("Failed to open optimizer tree");
+    match serde_json::to_vec(optimizer) {
+        Ok(bytes) => {
+            if let Err(e) = tree.insert(key, bytes) {
+                tracing::error!("failed to persist optimizer build for {key}: {e}");
+            }
+        }
+        Err(e) => tracing::error!("failed to serialize optimizer build for {key}: {e}"),
+    }
+}
+
+pub fn load_build(db: &sled::Db, key: &str) -> Option<Vec<Optimizer>> {
+    let tree = db.open_tree(TREE_NAME).ok()?;
+    let bytes = tree.get(key).ok()??;
+    serde_json::from_slice(&bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_a_saved_build() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let build = vec![
+            Optimizer {
+                label: "head".to_string(),
+                ids: vec![1, 2, 3],
+            },
+            Optimizer {
+                label: "chest".to_string(),
+                ids: vec![4, 5],
+            },
+        ];
+        let key = target_key("profile.json", "settings.json");
+
+        save_build(&db, &key, &build);
+        let loaded = load_build(&db, &key).expect("a saved build should load back");
+
+        assert_eq!(loaded, build);
+    }
+}